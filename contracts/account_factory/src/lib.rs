@@ -1,9 +1,10 @@
 //! Account Factory contract for Lumenitos wallet.
 //!
 //! This contract creates new simple_account contract instances using a
-//! deterministic address derived from the factory address + signer's public key.
+//! deterministic address derived from the factory address + signer key.
 #![no_std]
 
+use simple_account::{AccountConfig, Signature, SignerKey, SimpleAccount};
 use soroban_sdk::{contract, contractimpl, symbol_short, Address, BytesN, Env};
 
 #[contract]
@@ -11,29 +12,87 @@ pub struct AccountFactory;
 
 #[contractimpl]
 impl AccountFactory {
-    /// Initialize the factory with the simple_account WASM hash.
-    /// This hash is used to deploy new account instances.
-    pub fn __constructor(env: Env, wasm_hash: BytesN<32>) {
+    /// Initialize the factory with the simple_account WASM hash, and the
+    /// instance-storage TTL extension policy to apply to the factory's own
+    /// instance on every `create`/`create_with_config` call. Operators tune
+    /// these to control how aggressively the factory guards against being
+    /// archived between deployments.
+    pub fn __constructor(env: Env, wasm_hash: BytesN<32>, ttl_threshold: u32, ttl_extend_to: u32) {
         env.storage().instance().set(&symbol_short!("wasm"), &wasm_hash);
+        env.storage().instance().set(&symbol_short!("ttl_thr"), &ttl_threshold);
+        env.storage().instance().set(&symbol_short!("ttl_ext"), &ttl_extend_to);
     }
 
-    /// Create a new simple_account contract for the given owner public key.
+    /// Create a new simple_account contract for the given owner signer key.
     ///
-    /// No authorization required - anyone can deploy a contract for any public key.
+    /// No authorization required - anyone can deploy a contract for any signer key.
     /// This is safe because:
     /// 1. The contract address is deterministic (factory + salt)
     /// 2. Only the private key holder can use the deployed contract
     /// 3. Enables gasless onboarding (someone else can pay for deployment)
     ///
     /// # Arguments
-    /// * `owner_bytes` - The 32-byte ed25519 public key that will own the new contract
+    /// * `signer` - The ed25519 or secp256r1 key that will own the new contract
     ///
     /// # Returns
     /// The address of the newly deployed contract account (C...)
     ///
     /// # Panics
-    /// * If a contract already exists for this owner (same salt)
-    pub fn create(env: Env, owner_bytes: BytesN<32>) -> Address {
+    /// * If a contract already exists for this signer (same salt)
+    pub fn create(env: Env, signer: SignerKey) -> Address {
+        Self::create_with_config(
+            env,
+            signer,
+            AccountConfig {
+                guardians: None,
+                policy: None,
+                ttl_threshold: None,
+                ttl_extend_to: None,
+                webauthn: None,
+            },
+            None,
+        )
+    }
+
+    /// Create a new simple_account contract for the given owner signer key,
+    /// atomically setting up the given recovery/policy configuration in the
+    /// same deployment. Following the deployer pattern of deploying and
+    /// initializing atomically, this closes the window where a freshly
+    /// created account would otherwise sit unconfigured until a separate,
+    /// separately-authorized follow-up transaction.
+    ///
+    /// The salt is derived solely from `signer`, so the deterministic
+    /// address for a given signer is the same regardless of `config`.
+    ///
+    /// A non-default `config` (guardians and/or a policy) changes who can
+    /// ultimately control or restrict the deployed account, so it can't be
+    /// left open to whoever calls this function first: `config_sig` must
+    /// then be `signer`'s own signature over the deployment salt, proving
+    /// the caller holds `signer`'s private key. Plain deployment with the
+    /// default, unconfigured `AccountConfig` keeps the original "anyone can
+    /// deploy for any pubkey" behavior, since only the key holder can use
+    /// such an account anyway. The TTL and WebAuthn fields of `config` do not
+    /// require `config_sig`. The factory stores no `WebAuthnConfig`, so a
+    /// secp256r1 `config_sig` always fails verification here: a secp256r1
+    /// signer can only be deployed without guardians or a policy.
+    ///
+    /// # Arguments
+    /// * `signer` - The ed25519 or secp256r1 key that will own the new contract
+    /// * `config` - Optional guardians and policy to set up alongside the signer
+    /// * `config_sig` - Required when `config` sets guardians or a policy; `signer`'s signature over the deployment salt
+    ///
+    /// # Returns
+    /// The address of the newly deployed contract account (C...)
+    ///
+    /// # Panics
+    /// * If a contract already exists for this signer (same salt)
+    /// * If `config` sets guardians or a policy and `config_sig` is missing or invalid
+    pub fn create_with_config(
+        env: Env,
+        signer: SignerKey,
+        config: AccountConfig,
+        config_sig: Option<Signature>,
+    ) -> Address {
         // Get the WASM hash from storage
         let wasm_hash: BytesN<32> = env
             .storage()
@@ -41,11 +100,24 @@ impl AccountFactory {
             .get(&symbol_short!("wasm"))
             .expect("wasm_hash not set");
 
-        // Deploy the new contract using owner bytes as salt
-        // The constructor takes the public key as bytes
+        // Renew the factory's own instance on every deployment so it isn't
+        // archived between bursts of account creation.
+        let ttl_threshold: u32 = env.storage().instance().get(&symbol_short!("ttl_thr")).unwrap();
+        let ttl_extend_to: u32 = env.storage().instance().get(&symbol_short!("ttl_ext")).unwrap();
+        env.storage().instance().extend_ttl(ttl_threshold, ttl_extend_to);
+
+        let salt = Self::signer_salt(&env, &signer);
+
+        if config.guardians.is_some() || config.policy.is_some() {
+            let sig = config_sig.expect("config_sig required to deploy with guardians or policy");
+            SimpleAccount::verify_signer_signature(&env, &signer, &salt, &sig);
+        }
+
+        // Deploy the new contract using the signer-derived salt
+        // The constructor takes the signer key plus the config
         env.deployer()
-            .with_current_contract(owner_bytes.clone())
-            .deploy_v2(wasm_hash, (owner_bytes,))
+            .with_current_contract(salt)
+            .deploy_v2(wasm_hash, (signer, config))
     }
 
     /// Get the WASM hash used by this factory.
@@ -60,13 +132,121 @@ impl AccountFactory {
     /// Useful for checking if a contract already exists or for UI display.
     ///
     /// # Arguments
-    /// * `signer_bytes` - The 32-byte ed25519 public key
+    /// * `signer` - The ed25519 or secp256r1 key to compute the address for
     ///
     /// # Returns
     /// The contract address that would be created for this signer
-    pub fn get_address(env: Env, signer_bytes: BytesN<32>) -> Address {
-        env.deployer()
-            .with_current_contract(signer_bytes)
-            .deployed_address()
+    pub fn get_address(env: Env, signer: SignerKey) -> Address {
+        let salt = Self::signer_salt(&env, &signer);
+        env.deployer().with_current_contract(salt).deployed_address()
+    }
+
+    /// Derive the 32-byte deployer salt for a signer key. Ed25519 keys use
+    /// their own bytes directly, keeping existing deterministic addresses
+    /// unchanged; secp256r1 keys are longer than a salt, so they're hashed
+    /// down to 32 bytes to get their own distinct address.
+    fn signer_salt(env: &Env, signer: &SignerKey) -> BytesN<32> {
+        match signer {
+            SignerKey::Ed25519(pubkey) => pubkey.clone(),
+            SignerKey::Secp256r1(pubkey) => env.crypto().sha256(&pubkey.clone().into()).to_bytes(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+    use simple_account::Guardians;
+    use soroban_sdk::Vec;
+
+    mod simple_account_wasm {
+        soroban_sdk::contractimport!(
+            file = "../simple_account/target/wasm32-unknown-unknown/release/simple_account.wasm"
+        );
+    }
+
+    fn setup(env: &Env) -> AccountFactoryClient {
+        let wasm_hash = env.deployer().upload_contract_wasm(simple_account_wasm::WASM);
+        let factory_id = env.register(AccountFactory, (wasm_hash, 10u32, 100u32));
+        AccountFactoryClient::new(env, &factory_id)
+    }
+
+    fn signer_and_key(env: &Env) -> (SigningKey, SignerKey) {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let pubkey = BytesN::from_array(env, &signing_key.verifying_key().to_bytes());
+        (signing_key, SignerKey::Ed25519(pubkey))
+    }
+
+    fn config_with_guardians(env: &Env) -> AccountConfig {
+        let mut keys = Vec::new(env);
+        keys.push_back(BytesN::from_array(env, &[1u8; 32]));
+        AccountConfig {
+            guardians: Some(Guardians { keys, threshold: 1 }),
+            policy: None,
+            ttl_threshold: None,
+            ttl_extend_to: None,
+            webauthn: None,
+        }
+    }
+
+    #[test]
+    fn create_with_default_config_needs_no_signature() {
+        let env = Env::default();
+        let client = setup(&env);
+        let (_signing_key, signer) = signer_and_key(&env);
+
+        client.create(&signer);
+    }
+
+    #[test]
+    fn create_with_config_rejects_guardians_without_a_signature() {
+        let env = Env::default();
+        let client = setup(&env);
+        let (_signing_key, signer) = signer_and_key(&env);
+        let config = config_with_guardians(&env);
+
+        let result = client.try_create_with_config(&signer, &config, &None);
+        assert!(
+            result.is_err(),
+            "a non-default config must require proof of signer ownership"
+        );
+    }
+
+    #[test]
+    fn create_with_config_rejects_a_signature_from_the_wrong_key() {
+        let env = Env::default();
+        let client = setup(&env);
+        let (_signing_key, signer) = signer_and_key(&env);
+        let config = config_with_guardians(&env);
+
+        let wrong_key = SigningKey::from_bytes(&[9u8; 32]);
+        let salt = AccountFactory::signer_salt(&env, &signer);
+        let bad_sig = Signature::Ed25519(BytesN::from_array(
+            &env,
+            &wrong_key.sign(&salt.to_array()).to_bytes(),
+        ));
+
+        let result = client.try_create_with_config(&signer, &config, &Some(bad_sig));
+        assert!(
+            result.is_err(),
+            "a signature from a key other than the signer must be rejected"
+        );
+    }
+
+    #[test]
+    fn create_with_config_accepts_the_signer_own_signature() {
+        let env = Env::default();
+        let client = setup(&env);
+        let (signing_key, signer) = signer_and_key(&env);
+        let config = config_with_guardians(&env);
+
+        let salt = AccountFactory::signer_salt(&env, &signer);
+        let sig = Signature::Ed25519(BytesN::from_array(
+            &env,
+            &signing_key.sign(&salt.to_array()).to_bytes(),
+        ));
+
+        client.create_with_config(&signer, &config, &Some(sig));
     }
 }
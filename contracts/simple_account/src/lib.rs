@@ -1,10 +1,14 @@
 //! Simple account contract for Lumenitos wallet.
 //!
-//! This contract is owned by a single ed25519 public key that is also used for
-//! authentication. Based on the Soroban simple_account example.
+//! This contract is owned by a weighted set of signers: authorization
+//! succeeds once the signatures presented sum to at least the stored
+//! threshold. Based on the Soroban simple_account example.
 #![no_std]
 
-use soroban_sdk::{auth::Context, contract, contractimpl, contracttype, BytesN, Env, Vec};
+use soroban_sdk::{
+    auth::{Context, ContractContext},
+    contract, contractimpl, contracttype, Address, Bytes, BytesN, Env, Map, Symbol, Vec,
+};
 
 #[contract]
 pub struct SimpleAccount;
@@ -12,36 +16,1050 @@ pub struct SimpleAccount;
 #[derive(Clone)]
 #[contracttype]
 pub enum DataKey {
-    Owner,
+    Signers,
+    Threshold,
+    Policy,
+    Guardians,
+    PendingRecovery,
+    SessionKey(BytesN<32>),
+    TtlConfig,
+    WebAuthnConfig,
+}
+
+/// Instance-storage TTL extension parameters, applied on every authenticated
+/// use so a dormant wallet keeps renewing its own lifetime instead of being
+/// archived between uses.
+#[derive(Clone)]
+#[contracttype]
+pub struct TtlConfig {
+    pub threshold: u32,
+    pub extend_to: u32,
+}
+
+/// Defaults used when `AccountConfig` doesn't specify a TTL policy.
+const DEFAULT_TTL_THRESHOLD: u32 = 17_280; // ~1 day of ledgers at 5s close time
+const DEFAULT_TTL_EXTEND_TO: u32 = 518_400; // ~30 days of ledgers at 5s close time
+
+/// A signer's public key, tagged by scheme. `Ed25519` covers the original
+/// wallet keys; `Secp256r1` covers device/browser passkeys (WebAuthn), whose
+/// assertions are verified against the P-256 curve.
+#[derive(Clone, PartialEq, Eq)]
+#[contracttype]
+pub enum SignerKey {
+    Ed25519(BytesN<32>),
+    Secp256r1(BytesN<65>),
+}
+
+/// A signature over `signature_payload`, in the scheme matching its
+/// `SignerKey`. WebAuthn assertions don't sign the payload directly: they
+/// sign `sha256(authenticator_data || sha256(client_data_json))`, with the
+/// payload embedded as the challenge inside `client_data_json`.
+#[derive(Clone)]
+#[contracttype]
+pub enum Signature {
+    Ed25519(BytesN<64>),
+    Secp256r1(WebAuthnSignature),
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct WebAuthnSignature {
+    pub authenticator_data: Bytes,
+    pub client_data_json: Bytes,
+    pub signature: BytesN<64>,
+}
+
+/// What a WebAuthn/passkey assertion must be bound to in order to be
+/// accepted: the relying party this wallet expects (the sha256 of its RP
+/// ID, exactly as authenticators compute it into `authenticator_data`) and
+/// the origin the browser must report performing the ceremony from. Without
+/// checking both, any site a user's passkey will sign a `webauthn.get` for -
+/// including a phishing page that smuggles the on-chain payload in as the
+/// challenge - would produce a signature this contract accepts.
+#[derive(Clone)]
+#[contracttype]
+pub struct WebAuthnConfig {
+    pub rp_id_hash: BytesN<32>,
+    pub expected_origin: Bytes,
+}
+
+/// What a session key is allowed to authorize: a single contract, optionally
+/// narrowed to one function on it.
+#[derive(Clone)]
+#[contracttype]
+pub struct SessionScope {
+    pub contract: Address,
+    pub fn_name: Option<Symbol>,
+}
+
+/// A delegated, time-bounded signing key and the scope it's limited to.
+/// Stored in temporary storage so it is automatically archived once its TTL
+/// elapses, rather than lingering in instance storage past `expires_at`.
+#[derive(Clone)]
+#[contracttype]
+pub struct SessionKeyData {
+    pub expires_at: u64,
+    pub scope: SessionScope,
+}
+
+/// Rough ledger close time used to translate a session key's `expires_at`
+/// timestamp into a temporary-storage TTL extension.
+const APPROX_LEDGER_SECONDS: u64 = 5;
+
+/// Guardians permitted to approve social recovery, and how many approvals
+/// are required before a recovery can execute.
+#[derive(Clone)]
+#[contracttype]
+pub struct Guardians {
+    pub keys: Vec<BytesN<32>>,
+    pub threshold: u32,
+}
+
+/// A proposed owner-key recovery awaiting guardian approval and the timelock.
+#[derive(Clone)]
+#[contracttype]
+pub struct PendingRecovery {
+    pub new_owner: BytesN<32>,
+    pub approvals: Vec<BytesN<32>>,
+    pub unlock_at: u64,
+}
+
+/// How long a recovery must wait, counted from when it is proposed, before
+/// it can execute (it also needs enough guardian approvals). Gives the
+/// legitimate owner a window to cancel.
+const RECOVERY_TIMELOCK_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Optional extra setup that can be supplied atomically at deployment time,
+/// via `AccountFactory::create_with_config`, so a freshly created account is
+/// never left unconfigured between its deployment and a follow-up setup
+/// transaction.
+#[derive(Clone)]
+#[contracttype]
+pub struct AccountConfig {
+    pub guardians: Option<Guardians>,
+    pub policy: Option<Policy>,
+    /// Instance-storage TTL extension threshold, in ledgers. Defaults to
+    /// `DEFAULT_TTL_THRESHOLD` when unset.
+    pub ttl_threshold: Option<u32>,
+    /// Instance-storage TTL extension target, in ledgers. Defaults to
+    /// `DEFAULT_TTL_EXTEND_TO` when unset.
+    pub ttl_extend_to: Option<u32>,
+    /// Expected relying party / origin for WebAuthn assertions. Required
+    /// before a `Secp256r1` signer can ever authenticate (session keys are
+    /// ed25519 only).
+    pub webauthn: Option<WebAuthnConfig>,
+}
+
+/// Authorization policy restricting what the owner's signature may be used for.
+///
+/// When no policy is stored, a valid signature authorizes anything (the
+/// original, unrestricted behavior). Once a policy is set, every `Context`
+/// presented to `__check_auth` must satisfy it, except calls to the
+/// account's own address, which are always permitted.
+#[derive(Clone)]
+#[contracttype]
+pub struct Policy {
+    /// Contracts the owner is allowed to invoke. Empty means none are allowed.
+    pub allowed_contracts: Vec<Address>,
+    /// Functions the owner is allowed to call on an allowed contract. Empty
+    /// means any function on an allowed contract is permitted.
+    pub allowed_fns: Vec<Symbol>,
+    /// Per-contract cap on the number of calls authorized in a single
+    /// `__check_auth` invocation. A contract with no entry has no cap.
+    pub call_caps: Map<Address, u32>,
+    /// Whether this signature may authorize creating new contracts.
+    pub allow_create: bool,
 }
 
 #[contractimpl]
 impl SimpleAccount {
-    /// Initialize the account with the owner's ed25519 public key.
-    /// Can only be called once during contract deployment.
-    pub fn __constructor(env: Env, public_key: BytesN<32>) {
-        if env.storage().instance().has(&DataKey::Owner) {
-            panic!("owner is already set");
+    /// Initialize the account with a single owner signer key (ed25519 or a
+    /// secp256r1 passkey). This is the convenience path for the common
+    /// single-signer wallet: it registers the key as the sole signer with a
+    /// threshold of 1. `config` carries any guardians/policy to set up
+    /// atomically alongside the signer, so the account is never left
+    /// unconfigured. Can only be called once during contract deployment.
+    pub fn __constructor(env: Env, signer: SignerKey, config: AccountConfig) {
+        if env.storage().instance().has(&DataKey::Signers) {
+            panic!("signers are already set");
+        }
+        let mut signers: Map<SignerKey, u32> = Map::new(&env);
+        signers.set(signer, 1);
+        env.storage().instance().set(&DataKey::Signers, &signers);
+        env.storage().instance().set(&DataKey::Threshold, &1u32);
+
+        if let Some(guardians) = config.guardians {
+            Self::validate_guardians(&guardians);
+            env.storage().instance().set(&DataKey::Guardians, &guardians);
+        }
+        if let Some(policy) = config.policy {
+            env.storage().instance().set(&DataKey::Policy, &policy);
         }
-        env.storage().instance().set(&DataKey::Owner, &public_key);
+        if let Some(webauthn) = config.webauthn {
+            env.storage().instance().set(&DataKey::WebAuthnConfig, &webauthn);
+        }
+
+        let ttl_config = TtlConfig {
+            threshold: config.ttl_threshold.unwrap_or(DEFAULT_TTL_THRESHOLD),
+            extend_to: config.ttl_extend_to.unwrap_or(DEFAULT_TTL_EXTEND_TO),
+        };
+        env.storage().instance().set(&DataKey::TtlConfig, &ttl_config);
     }
 
     /// Verify authentication for contract invocations.
     /// This is called by the Soroban host when this contract's address
-    /// is used as a source for `require_auth`.
+    /// is used as a source for `require_auth`. `signature` carries one
+    /// `(SignerKey, Signature)` pair per co-signer, each verified against
+    /// its own scheme; authorization succeeds once the weights of the
+    /// validly-signed keys meet the stored threshold. A key that isn't a
+    /// registered signer is checked against the account's ed25519 session
+    /// keys instead, and if valid is scoped to whatever that session key
+    /// was authorized for.
     #[allow(non_snake_case)]
     pub fn __check_auth(
         env: Env,
         signature_payload: BytesN<32>,
-        signature: BytesN<64>,
-        _auth_context: Vec<Context>,
+        signature: Vec<(SignerKey, Signature)>,
+        auth_context: Vec<Context>,
+    ) {
+        let signers: Map<SignerKey, u32> =
+            env.storage().instance().get(&DataKey::Signers).unwrap();
+        let threshold: u32 = env.storage().instance().get(&DataKey::Threshold).unwrap();
+
+        let mut seen: Vec<SignerKey> = Vec::new(&env);
+        for (key, _) in signature.iter() {
+            if seen.contains(&key) {
+                panic!("duplicate signer in signature");
+            }
+            seen.push_back(key);
+        }
+
+        let mut weight = 0u32;
+        for (key, sig) in signature.iter() {
+            Self::verify_signer_signature(&env, &key, &signature_payload, &sig);
+
+            if let Some(signer_weight) = signers.get(key.clone()) {
+                weight += signer_weight;
+                continue;
+            }
+
+            let SignerKey::Ed25519(pubkey) = key else {
+                panic!("unknown signer");
+            };
+            let session_key = DataKey::SessionKey(pubkey);
+            let data: SessionKeyData = env
+                .storage()
+                .temporary()
+                .get(&session_key)
+                .unwrap_or_else(|| panic!("unknown signer"));
+            if env.ledger().timestamp() > data.expires_at {
+                panic!("session key has expired");
+            }
+            Self::enforce_scope(&data.scope, &auth_context);
+            weight = threshold;
+        }
+        if weight < threshold {
+            panic!("signature weight does not meet threshold");
+        }
+
+        if let Some(policy) = env.storage().instance().get::<_, Policy>(&DataKey::Policy) {
+            Self::enforce_policy(&env, &policy, &auth_context);
+        }
+
+        Self::bump_ttl(env);
+    }
+
+    /// Extend this account's instance storage TTL using its configured
+    /// threshold/extend-to, so a wallet that's used regularly never gets
+    /// archived. Called automatically on every successful `__check_auth`,
+    /// and exposed here so anyone can renew a dormant-but-still-live wallet.
+    pub fn bump_ttl(env: Env) {
+        let ttl: TtlConfig = env.storage().instance().get(&DataKey::TtlConfig).unwrap();
+        env.storage().instance().extend_ttl(ttl.threshold, ttl.extend_to);
+    }
+
+    /// Hash a WebAuthn assertion's authenticator/client data into the digest
+    /// that was actually signed, after checking that the ceremony is one
+    /// this wallet can trust: the embedded challenge matches the payload the
+    /// host asked us to authorize (base64url-encoded, as real clients embed
+    /// it), the client data says `"type":"webauthn.get"`, the origin
+    /// matches this wallet's configured `expected_origin`, and
+    /// `authenticator_data`'s leading `rpIdHash` matches this wallet's
+    /// configured relying party. The origin/rpIdHash checks are what give
+    /// WebAuthn its phishing resistance - without them, a signature
+    /// produced for any site the passkey happens to sign for would be
+    /// accepted here.
+    fn webauthn_digest(env: &Env, sig: &WebAuthnSignature, signature_payload: &BytesN<32>) -> BytesN<32> {
+        let config: WebAuthnConfig = env
+            .storage()
+            .instance()
+            .get(&DataKey::WebAuthnConfig)
+            .expect("no WebAuthn configuration set");
+
+        if sig.authenticator_data.len() < 32
+            || sig.authenticator_data.slice(0..32) != config.rp_id_hash.clone().into()
+        {
+            panic!("authenticator data rpIdHash does not match expected relying party");
+        }
+
+        let challenge = Self::base64url_encode(env, signature_payload);
+        if !Self::bytes_contains(&sig.client_data_json, &challenge) {
+            panic!("client data does not match signature payload");
+        }
+        if !Self::bytes_contains(
+            &sig.client_data_json,
+            &Bytes::from_slice(env, br#""type":"webauthn.get""#),
+        ) {
+            panic!("client data is not a webauthn.get assertion");
+        }
+
+        let mut expected_origin_field = Bytes::from_slice(env, br#""origin":""#);
+        expected_origin_field.append(&config.expected_origin);
+        expected_origin_field.append(&Bytes::from_slice(env, br#"""#));
+        if !Self::bytes_contains(&sig.client_data_json, &expected_origin_field) {
+            panic!("client data origin does not match expected origin");
+        }
+
+        let client_data_hash: BytesN<32> = env.crypto().sha256(&sig.client_data_json).to_bytes();
+        let mut signed = sig.authenticator_data.clone();
+        signed.append(&client_data_hash.into());
+        env.crypto().sha256(&signed).to_bytes()
+    }
+
+    /// Check that `haystack` contains `needle` as a contiguous subsequence.
+    fn bytes_contains(haystack: &Bytes, needle: &Bytes) -> bool {
+        let h_len = haystack.len();
+        let n_len = needle.len();
+        if n_len == 0 || n_len > h_len {
+            return false;
+        }
+        for start in 0..=(h_len - n_len) {
+            if haystack.slice(start..start + n_len) == *needle {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Unpadded base64url-encode, as used for the `challenge` field of a
+    /// WebAuthn `clientDataJSON`.
+    fn base64url_encode(env: &Env, data: &BytesN<32>) -> Bytes {
+        const ALPHABET: &[u8; 64] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+        let bytes = data.to_array();
+        let mut out = Bytes::new(env);
+        let mut chunks = bytes.chunks_exact(3);
+        for chunk in &mut chunks {
+            let n = ((chunk[0] as u32) << 16) | ((chunk[1] as u32) << 8) | (chunk[2] as u32);
+            out.push_back(ALPHABET[((n >> 18) & 0x3F) as usize]);
+            out.push_back(ALPHABET[((n >> 12) & 0x3F) as usize]);
+            out.push_back(ALPHABET[((n >> 6) & 0x3F) as usize]);
+            out.push_back(ALPHABET[(n & 0x3F) as usize]);
+        }
+        let rem = chunks.remainder();
+        if rem.len() == 1 {
+            let n = (rem[0] as u32) << 16;
+            out.push_back(ALPHABET[((n >> 18) & 0x3F) as usize]);
+            out.push_back(ALPHABET[((n >> 12) & 0x3F) as usize]);
+        } else if rem.len() == 2 {
+            let n = ((rem[0] as u32) << 16) | ((rem[1] as u32) << 8);
+            out.push_back(ALPHABET[((n >> 18) & 0x3F) as usize]);
+            out.push_back(ALPHABET[((n >> 12) & 0x3F) as usize]);
+            out.push_back(ALPHABET[((n >> 6) & 0x3F) as usize]);
+        }
+        out
+    }
+
+    /// Delegate a time-bounded, scoped signing key. Requires the account's
+    /// own authorization. The entry is written to temporary storage, which
+    /// Soroban automatically archives once its TTL elapses, so an expired
+    /// session key is garbage-collected rather than lingering forever.
+    pub fn add_session_key(
+        env: Env,
+        public_key: BytesN<32>,
+        expires_at: u64,
+        scope: SessionScope,
     ) {
-        let public_key: BytesN<32> = env
+        env.current_contract_address().require_auth();
+        let now = env.ledger().timestamp();
+        if expires_at <= now {
+            panic!("expires_at must be in the future");
+        }
+
+        let key = DataKey::SessionKey(public_key);
+        env.storage()
+            .temporary()
+            .set(&key, &SessionKeyData { expires_at, scope });
+
+        let ttl_ledgers = ((expires_at - now) / APPROX_LEDGER_SECONDS) as u32 + 1;
+        env.storage().temporary().extend_ttl(&key, ttl_ledgers, ttl_ledgers);
+    }
+
+    /// Add or update a signer's weight. Requires the account's own
+    /// authorization, so a change must itself meet the current threshold.
+    pub fn add_signer(env: Env, signer: SignerKey, weight: u32) {
+        env.current_contract_address().require_auth();
+        let mut signers: Map<SignerKey, u32> =
+            env.storage().instance().get(&DataKey::Signers).unwrap();
+        signers.set(signer, weight);
+        env.storage().instance().set(&DataKey::Signers, &signers);
+    }
+
+    /// Remove a signer. Requires the account's own authorization.
+    pub fn remove_signer(env: Env, signer: SignerKey) {
+        env.current_contract_address().require_auth();
+        let mut signers: Map<SignerKey, u32> =
+            env.storage().instance().get(&DataKey::Signers).unwrap();
+        signers.remove(signer);
+        env.storage().instance().set(&DataKey::Signers, &signers);
+    }
+
+    /// Set the signature weight threshold required to authorize. Requires
+    /// the account's own authorization. A zero threshold would make an empty
+    /// signature vector satisfy `__check_auth`, so it's rejected.
+    pub fn set_threshold(env: Env, threshold: u32) {
+        env.current_contract_address().require_auth();
+        if threshold == 0 {
+            panic!("threshold must be greater than zero");
+        }
+        env.storage().instance().set(&DataKey::Threshold, &threshold);
+    }
+
+    /// Restrict the account's signature to the given policy. Requires the
+    /// account's own authorization, so signatures meeting the current
+    /// threshold. The policy already in place does not restrict this call:
+    /// calls to the account's own address are always permitted by it.
+    pub fn set_policy(env: Env, policy: Policy) {
+        env.current_contract_address().require_auth();
+        env.storage().instance().set(&DataKey::Policy, &policy);
+    }
+
+    /// Remove the stored policy, returning to unrestricted authorization.
+    pub fn clear_policy(env: Env) {
+        env.current_contract_address().require_auth();
+        env.storage().instance().remove(&DataKey::Policy);
+    }
+
+    /// Set (or replace) the guardian set and recovery threshold. Requires
+    /// the account's own authorization.
+    pub fn set_guardians(env: Env, guardians: Guardians) {
+        env.current_contract_address().require_auth();
+        Self::validate_guardians(&guardians);
+        env.storage().instance().set(&DataKey::Guardians, &guardians);
+    }
+
+    /// A zero threshold would let a single guardian's approval satisfy
+    /// `execute_recovery` on its own, and a threshold above the number of
+    /// guardians could never be reached at all, so both are rejected.
+    fn validate_guardians(guardians: &Guardians) {
+        if guardians.threshold == 0 {
+            panic!("guardian threshold must be greater than zero");
+        }
+        if guardians.threshold > guardians.keys.len() {
+            panic!("guardian threshold cannot exceed the number of guardians");
+        }
+    }
+
+    /// Propose recovering the account to a new owner key. Must be initiated
+    /// by a guardian, who signs the proposed new owner key to prove it; that
+    /// guardian's approval is recorded immediately. It only takes effect
+    /// once enough guardians approve and the timelock elapses, and the
+    /// current owner can cancel it at any time via `cancel_recovery`. A
+    /// pending recovery that has already reached `guardians.threshold`
+    /// approvals is left in place rather than being reset, so a guardian
+    /// acting alone can't grief a recovery that's ready (or about to be
+    /// ready) to execute. Below that bar, any guardian may replace it with
+    /// a different proposal - otherwise a single guardian proposing a
+    /// recovery only they approve of would permanently block the other
+    /// guardians from ever proposing the legitimate one.
+    pub fn propose_recovery(env: Env, guardian_pubkey: BytesN<32>, sig: BytesN<64>, new_owner: BytesN<32>) {
+        let guardians: Guardians = env
             .storage()
             .instance()
-            .get::<_, BytesN<32>>(&DataKey::Owner)
-            .unwrap();
+            .get(&DataKey::Guardians)
+            .expect("no guardians configured");
+        if !guardians.keys.contains(&guardian_pubkey) {
+            panic!("not a guardian");
+        }
+        env.crypto()
+            .ed25519_verify(&guardian_pubkey, &new_owner.clone().into(), &sig);
+
+        if let Some(existing) = env
+            .storage()
+            .instance()
+            .get::<_, PendingRecovery>(&DataKey::PendingRecovery)
+        {
+            if (existing.approvals.len() as u32) >= guardians.threshold {
+                panic!("a pending recovery that has reached threshold already exists");
+            }
+        }
+
+        let mut approvals = Vec::new(&env);
+        approvals.push_back(guardian_pubkey);
+        let pending = PendingRecovery {
+            new_owner,
+            approvals,
+            unlock_at: env.ledger().timestamp() + RECOVERY_TIMELOCK_SECS,
+        };
+        env.storage().instance().set(&DataKey::PendingRecovery, &pending);
+    }
+
+    /// Record a guardian's approval of the pending recovery. Verifies that
+    /// `sig` is `guardian_pubkey`'s signature over the proposed new owner key.
+    pub fn approve_recovery(env: Env, guardian_pubkey: BytesN<32>, sig: BytesN<64>) {
+        let guardians: Guardians = env
+            .storage()
+            .instance()
+            .get(&DataKey::Guardians)
+            .expect("no guardians configured");
+        if !guardians.keys.contains(&guardian_pubkey) {
+            panic!("not a guardian");
+        }
+        let mut pending: PendingRecovery = env
+            .storage()
+            .instance()
+            .get(&DataKey::PendingRecovery)
+            .expect("no pending recovery");
+
         env.crypto()
-            .ed25519_verify(&public_key, &signature_payload.into(), &signature);
+            .ed25519_verify(&guardian_pubkey, &pending.new_owner.clone().into(), &sig);
+
+        if !pending.approvals.contains(&guardian_pubkey) {
+            pending.approvals.push_back(guardian_pubkey);
+        }
+        env.storage().instance().set(&DataKey::PendingRecovery, &pending);
+    }
+
+    /// Execute a pending recovery once enough guardians have approved and
+    /// the timelock has elapsed, replacing the signer set with the new
+    /// owner key as the sole signer.
+    pub fn execute_recovery(env: Env) {
+        let guardians: Guardians = env
+            .storage()
+            .instance()
+            .get(&DataKey::Guardians)
+            .expect("no guardians configured");
+        let pending: PendingRecovery = env
+            .storage()
+            .instance()
+            .get(&DataKey::PendingRecovery)
+            .expect("no pending recovery");
+
+        if (pending.approvals.len() as u32) < guardians.threshold {
+            panic!("not enough guardian approvals");
+        }
+        if env.ledger().timestamp() < pending.unlock_at {
+            panic!("recovery is still timelocked");
+        }
+
+        let mut signers: Map<SignerKey, u32> = Map::new(&env);
+        signers.set(SignerKey::Ed25519(pending.new_owner), 1);
+        env.storage().instance().set(&DataKey::Signers, &signers);
+        env.storage().instance().set(&DataKey::Threshold, &1u32);
+        env.storage().instance().remove(&DataKey::PendingRecovery);
+    }
+
+    /// Cancel a pending recovery. Requires the account's own authorization,
+    /// so the legitimate owner can reject a malicious recovery attempt
+    /// during the timelock window.
+    pub fn cancel_recovery(env: Env) {
+        env.current_contract_address().require_auth();
+        env.storage().instance().remove(&DataKey::PendingRecovery);
+    }
+
+    /// Check every context being authorized against a session key's scope,
+    /// panicking on the first violation.
+    fn enforce_scope(scope: &SessionScope, auth_context: &Vec<Context>) {
+        for context in auth_context.iter() {
+            match context {
+                Context::Contract(ContractContext {
+                    contract, fn_name, ..
+                }) => {
+                    if contract != scope.contract {
+                        panic!("session key not authorized for this contract");
+                    }
+                    if let Some(scoped_fn) = &scope.fn_name {
+                        if fn_name != *scoped_fn {
+                            panic!("session key not authorized for this function");
+                        }
+                    }
+                }
+                Context::CreateContractHostFn(_) | Context::CreateContractWithCtorHostFn(_) => {
+                    panic!("session key may not authorize contract creation");
+                }
+            }
+        }
+    }
+
+    /// Check every context being authorized against the stored policy,
+    /// panicking on the first violation. Calls back into the account's own
+    /// address (e.g. `set_policy`, `clear_policy`, `cancel_recovery`) are
+    /// always permitted regardless of policy: a policy is meant to restrict
+    /// what the owner's signature can do to *other* contracts, not to lock
+    /// the owner out of managing their own wallet, including out of
+    /// `clear_policy` itself.
+    fn enforce_policy(env: &Env, policy: &Policy, auth_context: &Vec<Context>) {
+        let self_address = env.current_contract_address();
+        let mut call_counts: Map<Address, u32> = Map::new(env);
+        for context in auth_context.iter() {
+            match context {
+                Context::Contract(ContractContext {
+                    contract, fn_name, ..
+                }) => {
+                    if contract == self_address {
+                        continue;
+                    }
+                    if !policy.allowed_contracts.contains(&contract) {
+                        panic!("contract not authorized by policy");
+                    }
+                    if !policy.allowed_fns.is_empty() && !policy.allowed_fns.contains(&fn_name) {
+                        panic!("function not authorized by policy");
+                    }
+                    let count = call_counts.get(contract.clone()).unwrap_or(0) + 1;
+                    if let Some(cap) = policy.call_caps.get(contract.clone()) {
+                        if count > cap {
+                            panic!("call cap exceeded for contract");
+                        }
+                    }
+                    call_counts.set(contract.clone(), count);
+                }
+                Context::CreateContractHostFn(_) | Context::CreateContractWithCtorHostFn(_) => {
+                    if !policy.allow_create {
+                        panic!("contract creation not authorized by policy");
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl SimpleAccount {
+    /// Verify that `sig` is a valid signature by `key` over `payload`,
+    /// dispatching on the signer's scheme. Panics if verification fails or
+    /// the signature's scheme doesn't match the key's. Not a contract
+    /// endpoint (this `impl` isn't `#[contractimpl]`) - shared by
+    /// `__check_auth` and by other contracts (e.g. `account_factory`) that
+    /// need to prove ownership of a `SignerKey` before acting on it. The
+    /// `Secp256r1` case checks the assertion against the `WebAuthnConfig` in
+    /// the current contract's instance storage and panics where none is
+    /// stored, so in a contract without one (such as `account_factory`)
+    /// only `Ed25519` keys can be verified.
+    pub fn verify_signer_signature(
+        env: &Env,
+        key: &SignerKey,
+        payload: &BytesN<32>,
+        sig: &Signature,
+    ) {
+        match (key.clone(), sig.clone()) {
+            (SignerKey::Ed25519(pubkey), Signature::Ed25519(sig_bytes)) => {
+                env.crypto()
+                    .ed25519_verify(&pubkey, &payload.clone().into(), &sig_bytes);
+            }
+            (SignerKey::Secp256r1(pubkey), Signature::Secp256r1(webauthn)) => {
+                let digest = Self::webauthn_digest(env, &webauthn, payload);
+                env.crypto()
+                    .secp256r1_verify(&pubkey, &digest, &webauthn.signature);
+            }
+            _ => panic!("signature scheme does not match signer key"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+    use soroban_sdk::testutils::{Address as _, Ledger as _};
+    use soroban_sdk::IntoVal;
+
+    fn keypair(env: &Env) -> (SigningKey, SignerKey) {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let verifying_key = BytesN::from_array(env, &signing_key.verifying_key().to_bytes());
+        (signing_key, SignerKey::Ed25519(verifying_key))
+    }
+
+    fn sign(env: &Env, signing_key: &SigningKey, payload: &BytesN<32>) -> Signature {
+        let sig = signing_key.sign(&payload.to_array());
+        Signature::Ed25519(BytesN::from_array(env, &sig.to_bytes()))
+    }
+
+    fn register_account(env: &Env, signer: &SignerKey) -> SimpleAccountClient {
+        register_account_with_config(
+            env,
+            signer,
+            AccountConfig {
+                guardians: None,
+                policy: None,
+                ttl_threshold: None,
+                ttl_extend_to: None,
+                webauthn: None,
+            },
+        )
+    }
+
+    fn register_account_with_config(
+        env: &Env,
+        signer: &SignerKey,
+        config: AccountConfig,
+    ) -> SimpleAccountClient {
+        let contract_id = env.register(SimpleAccount, (signer.clone(), config));
+        SimpleAccountClient::new(env, &contract_id)
+    }
+
+    #[test]
+    fn check_auth_rejects_duplicate_signer() {
+        let env = Env::default();
+        let (signing_key, signer) = keypair(&env);
+        let client = register_account(&env, &signer);
+
+        let payload = BytesN::from_array(&env, &[1u8; 32]);
+        let sig = sign(&env, &signing_key, &payload);
+
+        let mut signature = Vec::new(&env);
+        signature.push_back((signer.clone(), sig.clone()));
+        signature.push_back((signer, sig));
+
+        let result = env.try_invoke_contract_check_auth::<soroban_sdk::Error>(
+            &client.address,
+            &payload,
+            signature.into_val(&env),
+            &Vec::new(&env),
+        );
+        assert!(result.is_err(), "duplicate signer must not meet threshold twice");
+    }
+
+    #[test]
+    fn check_auth_accepts_single_valid_signer() {
+        let env = Env::default();
+        let (signing_key, signer) = keypair(&env);
+        let client = register_account(&env, &signer);
+
+        let payload = BytesN::from_array(&env, &[2u8; 32]);
+        let sig = sign(&env, &signing_key, &payload);
+
+        let mut signature = Vec::new(&env);
+        signature.push_back((signer, sig));
+
+        let result = env.try_invoke_contract_check_auth::<soroban_sdk::Error>(
+            &client.address,
+            &payload,
+            signature.into_val(&env),
+            &Vec::new(&env),
+        );
+        assert!(result.is_ok(), "a single valid signer must meet a threshold of 1");
+    }
+
+    #[test]
+    fn set_threshold_rejects_zero() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let (_signing_key, signer) = keypair(&env);
+        let client = register_account(&env, &signer);
+
+        let result = client.try_set_threshold(&0);
+        assert!(result.is_err(), "a zero threshold must be rejected");
+    }
+
+    #[test]
+    fn webauthn_challenge_must_be_base64url_encoded() {
+        let env = Env::default();
+        let payload = BytesN::from_array(&env, &[9u8; 32]);
+        let challenge = SimpleAccount::base64url_encode(&env, &payload);
+
+        let mut client_data_json = Bytes::from_slice(&env, br#"{"type":"webauthn.get","challenge":""#);
+        client_data_json.append(&challenge);
+        client_data_json.append(&Bytes::from_slice(&env, br#"","origin":"https://example.com"}"#));
+
+        assert!(SimpleAccount::bytes_contains(&client_data_json, &challenge));
+
+        let raw_payload = Bytes::from_slice(&env, &payload.to_array());
+        assert!(!SimpleAccount::bytes_contains(&client_data_json, &raw_payload));
+    }
+
+    #[test]
+    fn session_key_rejected_outside_its_scope() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let (_owner_key, owner) = keypair(&env);
+        let client = register_account(&env, &owner);
+
+        let session_signing_key = SigningKey::from_bytes(&[42u8; 32]);
+        let session_pubkey =
+            BytesN::from_array(&env, &session_signing_key.verifying_key().to_bytes());
+        let scoped_contract = Address::generate(&env);
+        client.add_session_key(
+            &session_pubkey,
+            &(env.ledger().timestamp() + 1000),
+            &SessionScope {
+                contract: scoped_contract,
+                fn_name: None,
+            },
+        );
+
+        let payload = BytesN::from_array(&env, &[5u8; 32]);
+        let sig = Signature::Ed25519(BytesN::from_array(
+            &env,
+            &session_signing_key.sign(&payload.to_array()).to_bytes(),
+        ));
+        let mut signature = Vec::new(&env);
+        signature.push_back((SignerKey::Ed25519(session_pubkey), sig));
+
+        let other_contract = Address::generate(&env);
+        let mut auth_context = Vec::new(&env);
+        auth_context.push_back(Context::Contract(ContractContext {
+            contract: other_contract,
+            fn_name: Symbol::new(&env, "transfer"),
+            args: Vec::new(&env),
+        }));
+
+        let result = env.try_invoke_contract_check_auth::<soroban_sdk::Error>(
+            &client.address,
+            &payload,
+            signature.into_val(&env),
+            &auth_context,
+        );
+        assert!(result.is_err(), "session key must not authorize outside its scope");
+    }
+
+    #[test]
+    fn policy_rejects_call_to_contract_not_on_allow_list() {
+        let env = Env::default();
+        let (signing_key, signer) = keypair(&env);
+        let allowed_contract = Address::generate(&env);
+        let mut allowed_contracts = Vec::new(&env);
+        allowed_contracts.push_back(allowed_contract);
+        let client = register_account_with_config(
+            &env,
+            &signer,
+            AccountConfig {
+                guardians: None,
+                policy: Some(Policy {
+                    allowed_contracts,
+                    allowed_fns: Vec::new(&env),
+                    call_caps: Map::new(&env),
+                    allow_create: false,
+                }),
+                ttl_threshold: None,
+                ttl_extend_to: None,
+                webauthn: None,
+            },
+        );
+
+        let payload = BytesN::from_array(&env, &[3u8; 32]);
+        let sig = sign(&env, &signing_key, &payload);
+        let mut signature = Vec::new(&env);
+        signature.push_back((signer, sig));
+
+        let other_contract = Address::generate(&env);
+        let mut auth_context = Vec::new(&env);
+        auth_context.push_back(Context::Contract(ContractContext {
+            contract: other_contract,
+            fn_name: Symbol::new(&env, "transfer"),
+            args: Vec::new(&env),
+        }));
+
+        let result = env.try_invoke_contract_check_auth::<soroban_sdk::Error>(
+            &client.address,
+            &payload,
+            signature.into_val(&env),
+            &auth_context,
+        );
+        assert!(result.is_err(), "policy must reject a contract not on the allow list");
+    }
+
+    #[test]
+    fn policy_permits_self_calls_even_when_not_on_allow_list() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let (signing_key, signer) = keypair(&env);
+        // A policy with no allowed contracts at all - it only ever restricts
+        // calls to *other* contracts, never the account's own admin calls.
+        let client = register_account_with_config(
+            &env,
+            &signer,
+            AccountConfig {
+                guardians: None,
+                policy: Some(Policy {
+                    allowed_contracts: Vec::new(&env),
+                    allowed_fns: Vec::new(&env),
+                    call_caps: Map::new(&env),
+                    allow_create: false,
+                }),
+                ttl_threshold: None,
+                ttl_extend_to: None,
+                webauthn: None,
+            },
+        );
+
+        // clear_policy self-authorizes via require_auth, which surfaces as a
+        // Context::Contract entry whose contract is the account itself; this
+        // must succeed even though the policy's allow list is empty.
+        let payload = BytesN::from_array(&env, &[6u8; 32]);
+        let sig = sign(&env, &signing_key, &payload);
+        let mut signature = Vec::new(&env);
+        signature.push_back((signer, sig));
+        let mut auth_context = Vec::new(&env);
+        auth_context.push_back(Context::Contract(ContractContext {
+            contract: client.address.clone(),
+            fn_name: Symbol::new(&env, "clear_policy"),
+            args: Vec::new(&env),
+        }));
+        let result = env.try_invoke_contract_check_auth::<soroban_sdk::Error>(
+            &client.address,
+            &payload,
+            signature.into_val(&env),
+            &auth_context,
+        );
+        assert!(result.is_ok(), "policy must permit calls to the account itself");
+
+        client.clear_policy();
+    }
+
+    fn guardian(env: &Env, seed: u8) -> (SigningKey, BytesN<32>) {
+        let signing_key = SigningKey::from_bytes(&[seed; 32]);
+        let pubkey = BytesN::from_array(env, &signing_key.verifying_key().to_bytes());
+        (signing_key, pubkey)
+    }
+
+    fn register_account_with_guardians(
+        env: &Env,
+        signer: &SignerKey,
+        guardians: Guardians,
+    ) -> SimpleAccountClient {
+        register_account_with_config(
+            env,
+            signer,
+            AccountConfig {
+                guardians: Some(guardians),
+                policy: None,
+                ttl_threshold: None,
+                ttl_extend_to: None,
+                webauthn: None,
+            },
+        )
+    }
+
+    #[test]
+    fn recovery_rejects_proposal_from_non_guardian() {
+        let env = Env::default();
+        let (_owner_key, owner) = keypair(&env);
+        let (guardian1_key, guardian1) = guardian(&env, 11);
+        let mut keys = Vec::new(&env);
+        keys.push_back(guardian1);
+        let client = register_account_with_guardians(&env, &owner, Guardians { keys, threshold: 1 });
+
+        let (_not_a_guardian_key, not_a_guardian) = guardian(&env, 99);
+        let new_owner_key = guardian(&env, 21).1;
+        let sig = BytesN::from_array(
+            &env,
+            &guardian1_key.sign(&new_owner_key.to_array()).to_bytes(),
+        );
+
+        let result = client.try_propose_recovery(&not_a_guardian, &sig, &new_owner_key);
+        assert!(result.is_err(), "a non-guardian must not be able to propose recovery");
+    }
+
+    #[test]
+    fn recovery_executes_once_threshold_met_and_timelock_elapsed() {
+        let env = Env::default();
+        let (_owner_key, owner) = keypair(&env);
+        let (guardian1_key, guardian1) = guardian(&env, 11);
+        let (guardian2_key, guardian2) = guardian(&env, 12);
+        let mut keys = Vec::new(&env);
+        keys.push_back(guardian1.clone());
+        keys.push_back(guardian2.clone());
+        let client = register_account_with_guardians(&env, &owner, Guardians { keys, threshold: 2 });
+
+        let (_new_owner_key, new_owner) = guardian(&env, 21);
+        let sig1 = BytesN::from_array(&env, &guardian1_key.sign(&new_owner.to_array()).to_bytes());
+        client.propose_recovery(&guardian1, &sig1, &new_owner);
+
+        let sig2 = BytesN::from_array(&env, &guardian2_key.sign(&new_owner.to_array()).to_bytes());
+        client.approve_recovery(&guardian2, &sig2);
+
+        let result = client.try_execute_recovery();
+        assert!(result.is_err(), "recovery must not execute before the timelock elapses");
+
+        env.ledger()
+            .with_mut(|li| li.timestamp += RECOVERY_TIMELOCK_SECS + 1);
+        client.execute_recovery();
+
+        let payload = BytesN::from_array(&env, &[4u8; 32]);
+        let sig = Signature::Ed25519(BytesN::from_array(
+            &env,
+            &_new_owner_key.sign(&payload.to_array()).to_bytes(),
+        ));
+        let mut signature = Vec::new(&env);
+        signature.push_back((SignerKey::Ed25519(new_owner), sig));
+        let result = env.try_invoke_contract_check_auth::<soroban_sdk::Error>(
+            &client.address,
+            &payload,
+            signature.into_val(&env),
+            &Vec::new(&env),
+        );
+        assert!(result.is_ok(), "the recovered owner key must authorize");
+    }
+
+    #[test]
+    fn recovery_proposal_can_be_overridden_below_threshold_but_not_above() {
+        let env = Env::default();
+        let (_owner_key, owner) = keypair(&env);
+        let (guardian1_key, guardian1) = guardian(&env, 11);
+        let (guardian2_key, guardian2) = guardian(&env, 12);
+        let mut keys = Vec::new(&env);
+        keys.push_back(guardian1.clone());
+        keys.push_back(guardian2.clone());
+        let client = register_account_with_guardians(&env, &owner, Guardians { keys, threshold: 2 });
+
+        // A single rogue guardian proposes a recovery only they approve of.
+        let (_bad_owner_key, bad_owner) = guardian(&env, 31);
+        let bad_sig = BytesN::from_array(&env, &guardian1_key.sign(&bad_owner.to_array()).to_bytes());
+        client.propose_recovery(&guardian1, &bad_sig, &bad_owner);
+
+        // Below threshold (1 of 2 approvals): the other guardian can replace
+        // it with the recovery they actually want.
+        let (_good_owner_key, good_owner) = guardian(&env, 32);
+        let good_sig = BytesN::from_array(&env, &guardian2_key.sign(&good_owner.to_array()).to_bytes());
+        client.propose_recovery(&guardian2, &good_sig, &good_owner);
+
+        // Now at threshold: guardian1 also approves the good proposal, so
+        // guardian1 and guardian2 have both approved it.
+        let good_sig_from_1 =
+            BytesN::from_array(&env, &guardian1_key.sign(&good_owner.to_array()).to_bytes());
+        client.approve_recovery(&guardian1, &good_sig_from_1);
+
+        let (_other_owner_key, other_owner) = guardian(&env, 33);
+        let other_sig =
+            BytesN::from_array(&env, &guardian1_key.sign(&other_owner.to_array()).to_bytes());
+        let result = client.try_propose_recovery(&guardian1, &other_sig, &other_owner);
+        assert!(
+            result.is_err(),
+            "a proposal that has already reached threshold must not be replaceable"
+        );
+    }
+
+    #[test]
+    fn cancel_recovery_requires_owner_auth() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let (_owner_key, owner) = keypair(&env);
+        let (guardian1_key, guardian1) = guardian(&env, 11);
+        let mut keys = Vec::new(&env);
+        keys.push_back(guardian1.clone());
+        let client = register_account_with_guardians(&env, &owner, Guardians { keys, threshold: 1 });
+
+        let (_new_owner_key, new_owner) = guardian(&env, 21);
+        let sig = BytesN::from_array(&env, &guardian1_key.sign(&new_owner.to_array()).to_bytes());
+        client.propose_recovery(&guardian1, &sig, &new_owner);
+
+        client.cancel_recovery();
+        assert_eq!(
+            env.auths()[0].0,
+            client.address,
+            "cancel_recovery must require the account's own authorization"
+        );
+        let result = client.try_approve_recovery(&guardian1, &sig);
+        assert!(result.is_err(), "there must be no pending recovery left to approve");
     }
 }